@@ -5,6 +5,13 @@ use std::rc::Rc;
 /// A location in source code.
 ///
 /// Stores both the bytewise [position](#structfield.pos) and the logical [line](#structfield.row) and [character](#structfield.col) numbers.
+///
+/// `row`/`col` stay on `Loc` itself rather than being resolved lazily through a `SourceMap`, as
+/// [`SourceMap::lookup_line_col`](../source_map/struct.SourceMap.html#method.lookup_line_col) might
+/// suggest: the lexer/parser that would need to migrate to passing around bare `pos`es and resolving
+/// `row`/`col` only on demand isn't part of this tree, and `SpanT`'s `Display` impl still needs a row and
+/// column to print without a `SourceMap` in hand. `SourceMap` is the forward-looking path for new code;
+/// `Loc` keeps its eager fields until the callers that would let it shrink exist.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Loc {
     /// 0-based byte index.
@@ -13,16 +20,40 @@ pub struct Loc {
     pub row: usize,
     /// 0-based character number on the line.
     pub col: usize,
+    /// 0-based *visual* column on the line: tabs expand to the next tab stop, wide characters count as
+    /// two columns, and zero-width characters count as none. Equal to `col` unless the line contains one
+    /// of those. Use [`col`](#structfield.col) instead when a byte/char-exact position is needed.
+    display_col: usize,
 }
 
 impl Loc {
     /// Creates a new `Loc` with the given positions.
+    ///
+    /// `display_col` is initialized to `col`; call [`with_display_col`](#method.with_display_col) if the
+    /// line contains tabs or wide/zero-width characters and a true visual column is known.
     #[inline]
     pub fn new(pos: usize, row: usize, col: usize) -> Self {
         Loc {
             pos,
             row,
             col,
+            display_col: col,
+        }
+    }
+
+    /// The *visual* column; see the [field docs](#structfield.display_col) for how it differs from `col`.
+    #[inline]
+    pub fn display_col(&self) -> usize {
+        self.display_col
+    }
+
+    /// Returns a copy of this `Loc` with `display_col` overridden, e.g. with a value computed by
+    /// [`SourceMap::lookup_line_col`](../source_map/struct.SourceMap.html#method.lookup_line_col).
+    #[inline]
+    pub fn with_display_col(self, display_col: usize) -> Self {
+        Loc {
+            display_col,
+            ..self
         }
     }
 
@@ -102,6 +133,13 @@ impl<F> SpanT<F> {
         SpanT::new(file_name, Default::default(), Default::default())
     }
 
+    /// Extends this `SpanT` to also cover `span`, keeping `self`'s file name.
+    ///
+    /// Only meaningful when both spans are already known to share a file; `Loc::min`/`max` compare bare
+    /// positions, so covering two spans from different files produces a nonsensical range instead of an
+    /// error. For spans resolved through a [`SourceMap`](../source_map/struct.SourceMap.html), use
+    /// [`SourceMap::merge_spans`](../source_map/struct.SourceMap.html#method.merge_spans) instead, which
+    /// checks the file boundary for you.
     #[inline]
     pub fn extend_to_cover(self, span: SpanT<F>) -> Self {
         SpanT {
@@ -134,15 +172,17 @@ impl<'f> Span<'f> {
 }
 
 impl<F: fmt::Display> fmt::Display for SpanT<F> {
+    /// Prints the *visual* column (see [`Loc::display_col`](struct.Loc.html#structfield.display_col)),
+    /// so sources containing tabs or wide/zero-width characters line up with what an editor shows.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if self.start.row == self.end.row {
-            if self.start.col == self.end.col {
+            if self.start.display_col() == self.end.display_col() {
                 write!(
                     f,
                     "{}:{},{}",
                     self.file_name,
                     self.start.row + 1,
-                    self.start.col + 1,
+                    self.start.display_col() + 1,
                 )
             } else {
                 write!(
@@ -150,8 +190,8 @@ impl<F: fmt::Display> fmt::Display for SpanT<F> {
                     "{}:{},{}-{}",
                     self.file_name,
                     self.start.row + 1,
-                    self.start.col + 1,
-                    self.end.col + 1,
+                    self.start.display_col() + 1,
+                    self.end.display_col() + 1,
                 )
             }
         } else {
@@ -160,9 +200,9 @@ impl<F: fmt::Display> fmt::Display for SpanT<F> {
                 "{}:{},{}-{},{}",
                 self.file_name,
                 self.start.row + 1,
-                self.start.col + 1,
+                self.start.display_col() + 1,
                 self.end.row + 1,
-                self.end.col + 1,
+                self.end.display_col() + 1,
             )
         }
     }
@@ -0,0 +1,637 @@
+//! A central registry of loaded source files and the flat byte-offset coordinate space they share.
+use std::rc::Rc;
+
+use ast::{Loc, SpanT};
+
+/// A byte offset into the flat, global coordinate space shared by every [`SourceFile`](struct.SourceFile.html)
+/// in a [`SourceMap`](struct.SourceMap.html).
+///
+/// Unlike a plain `usize`, a `BytePos` is only meaningful relative to the `SourceMap` that produced it;
+/// use [`SourceMap::lookup_file`](struct.SourceMap.html#method.lookup_file) to recover the file it falls in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BytePos(pub u32);
+
+impl BytePos {
+    #[inline]
+    pub fn to_usize(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A region of the flat, global coordinate space, as a pair of [`BytePos`](struct.BytePos.html)s.
+///
+/// Unlike [`SpanT`](../ast/struct.SpanT.html), it carries no file name and no row/col: once every loaded
+/// source lives in one `SourceMap`, a pair of byte offsets is enough to identify a region.
+/// `SourceMap::lookup_file` recovers which file it belongs to, and `SourceMap::lookup_line_col` its
+/// row/col. `SourceMap` has no public method that turns a `Span` into a `SpanT`; only
+/// [`span_to_diagnostic_string`](struct.SourceMap.html#method.span_to_diagnostic_string) builds one,
+/// internally, for its header line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// The (inclusive) starting position.
+    pub lo: BytePos,
+    /// The (exclusive) ending position.
+    pub hi: BytePos,
+}
+
+impl Span {
+    #[inline]
+    pub fn new(lo: BytePos, hi: BytePos) -> Self {
+        Span { lo, hi }
+    }
+}
+
+/// A tab or non-ASCII character that takes up other than one column when displayed, as recorded by
+/// [`analyze_source_file`](fn.analyze_source_file.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NonNarrow {
+    /// A tab; expands to the next tab stop when computing a visual column.
+    Tab,
+    /// A zero-width character, such as a combining mark; contributes no visual width.
+    ZeroWidth,
+    /// A wide character, such as most CJK ideographs; occupies two visual columns.
+    Wide,
+}
+
+/// The result of scanning a source file once at load time, as produced by
+/// [`analyze_source_file`](fn.analyze_source_file.html).
+///
+/// Keeping these tables instead of a `row`/`col` per [`Loc`](../ast/struct.Loc.html) lets the lexer pass
+/// around bare byte positions; a row/col is only computed, via
+/// [`SourceMap::lookup_line_col`](struct.SourceMap.html#method.lookup_line_col), when one is actually
+/// needed, e.g. to format a diagnostic.
+#[derive(Debug, Default)]
+struct SourceFileAnalysis {
+    /// The start position of each line, including a first entry for line 0 and, if the file ends with a
+    /// newline, a final entry for the trailing empty line.
+    lines: Vec<BytePos>,
+    /// The position and UTF-8 byte length of every non-ASCII character, in order.
+    multibyte_chars: Vec<(BytePos, u8)>,
+    /// The position and kind of every tab and wide/zero-width character, in order.
+    non_narrow_chars: Vec<(BytePos, NonNarrow)>,
+}
+
+/// Scans `src` once, recording line starts and the positions of multibyte and non-narrow characters.
+///
+/// `start_pos` is the position `src`'s first byte will occupy in a `SourceMap`'s global coordinate space,
+/// so the returned positions can be used directly once `src` has been loaded into that map.
+fn analyze_source_file(src: &str, start_pos: BytePos) -> SourceFileAnalysis {
+    let mut lines = vec![start_pos];
+    let mut multibyte_chars = Vec::new();
+    let mut non_narrow_chars = Vec::new();
+
+    let mut chars = src.char_indices().peekable();
+    while let Some((idx, ch)) = chars.next() {
+        let pos = BytePos(start_pos.0 + idx as u32);
+        match ch {
+            '\n' => lines.push(BytePos(pos.0 + 1)),
+            // A lone `\r` does not start a new line; `\r\n` is a single line break.
+            '\r' if chars.peek().map(|&(_, c)| c) == Some('\n') => {
+                let (nidx, _) = chars.next().unwrap();
+                lines.push(BytePos(start_pos.0 + nidx as u32 + 1));
+            }
+            '\t' => non_narrow_chars.push((pos, NonNarrow::Tab)),
+            c if !c.is_ascii() => {
+                multibyte_chars.push((pos, c.len_utf8() as u8));
+                if let Some(non_narrow) = non_narrow_width(c) {
+                    non_narrow_chars.push((pos, non_narrow));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    SourceFileAnalysis { lines, multibyte_chars, non_narrow_chars }
+}
+
+/// Classifies a non-ASCII character as zero-width or double-width, or `None` if it displays as a single
+/// ordinary column. An approximation of Unicode East Asian Width / combining-mark data, good enough for
+/// diagnostic alignment without pulling in a full width table.
+fn non_narrow_width(c: char) -> Option<NonNarrow> {
+    match c as u32 {
+        0x300..=0x36F | 0x200B | 0x200C | 0x200D | 0xFEFF => Some(NonNarrow::ZeroWidth),
+        0x1100..=0x115F
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x20000..=0x3FFFD => Some(NonNarrow::Wide),
+        _ => None,
+    }
+}
+
+/// The number of visual columns a [`NonNarrow`](enum.NonNarrow.html) character occupies, given the visual
+/// column `col_v` it would start at (only relevant for tabs, which expand to the next
+/// multiple-of-`TAB_SIZE` stop).
+fn non_narrow_col_width(kind: NonNarrow, col_v: usize) -> usize {
+    match kind {
+        NonNarrow::Tab => TAB_SIZE - (col_v % TAB_SIZE),
+        NonNarrow::Wide => 2,
+        NonNarrow::ZeroWidth => 0,
+    }
+}
+
+/// The number of visual columns `ch` occupies, given the visual column `col_v` it would start at.
+fn visual_width(ch: char, col_v: usize) -> usize {
+    match ch {
+        '\t' => non_narrow_col_width(NonNarrow::Tab, col_v),
+        c => match non_narrow_width(c) {
+            Some(kind) => non_narrow_col_width(kind, col_v),
+            None => 1,
+        },
+    }
+}
+
+/// A single source file loaded into a [`SourceMap`](struct.SourceMap.html).
+///
+/// Occupies the contiguous range `[start_pos, start_pos + src.len())` in the map's global `BytePos` space.
+#[derive(Debug)]
+pub struct SourceFile {
+    /// The name of the source code, often a file path but sometimes a synthetic name like `<input>`.
+    pub name: String,
+    /// The full source text of the file.
+    pub src: String,
+    /// The position of the first byte of this file in the `SourceMap`'s global coordinate space.
+    pub start_pos: BytePos,
+    /// The start offset of each line; see [`SourceFileAnalysis::lines`](struct.SourceFileAnalysis.html).
+    lines: Vec<BytePos>,
+    /// Positions and byte lengths of non-ASCII characters, in order.
+    multibyte_chars: Vec<(BytePos, u8)>,
+    /// Positions and kinds of tabs and wide/zero-width characters, in order.
+    non_narrow_chars: Vec<(BytePos, NonNarrow)>,
+}
+
+impl SourceFile {
+    /// The position just past the last byte of this file in the `SourceMap`'s global coordinate space.
+    #[inline]
+    pub fn end_pos(&self) -> BytePos {
+        BytePos(self.start_pos.0 + self.src.len() as u32)
+    }
+}
+
+/// A resolved, 0-based line and true character column, as returned by
+/// [`SourceMap::lookup_line_col`](struct.SourceMap.html#method.lookup_line_col).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    /// 0-based line number.
+    pub line: usize,
+    /// 0-based character (not byte) column on the line.
+    pub col: usize,
+    /// 0-based *visual* column on the line; see [`Loc::display_col`](../ast/struct.Loc.html#structfield.display_col).
+    pub display_col: usize,
+}
+
+/// Tab stops, in columns, used when expanding a `\t` into a visual column.
+const TAB_SIZE: usize = 4;
+
+/// Owns every source file loaded during a compilation and assigns each one a disjoint slice of a single,
+/// monotonically increasing `BytePos` space.
+///
+/// Positions from different files are never mistaken for one another, since `SourceMap` is the only way
+/// to turn a `BytePos` back into a file, row, and column.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    /// Loaded files, kept sorted by `start_pos` so [`lookup_file`](#method.lookup_file) can binary-search.
+    files: Vec<Rc<SourceFile>>,
+}
+
+impl SourceMap {
+    /// Creates a new, empty `SourceMap`.
+    #[inline]
+    pub fn new() -> Self {
+        SourceMap { files: Vec::new() }
+    }
+
+    /// Loads a source file into the map, appending it to the global `BytePos` space, and returns it.
+    ///
+    /// A single byte of padding separates each file from the next so that an end-of-file position never
+    /// coincides with the start of the following file.
+    pub fn load_file(&mut self, name: impl Into<String>, src: impl Into<String>) -> Rc<SourceFile> {
+        let src = src.into();
+        let start_pos = BytePos(
+            self.files
+                .last()
+                .map(|f| f.end_pos().0 + 1)
+                .unwrap_or(0)
+        );
+        let analysis = analyze_source_file(&src, start_pos);
+        let file = Rc::new(SourceFile {
+            name: name.into(),
+            src,
+            start_pos,
+            lines: analysis.lines,
+            multibyte_chars: analysis.multibyte_chars,
+            non_narrow_chars: analysis.non_narrow_chars,
+        });
+        self.files.push(file.clone());
+        file
+    }
+
+    /// Looks up the `SourceFile` containing `pos`, by binary-searching for the file with the largest
+    /// `start_pos <= pos`.
+    pub fn lookup_file(&self, pos: BytePos) -> Option<&Rc<SourceFile>> {
+        match self.files.binary_search_by_key(&pos, |f| f.start_pos) {
+            Ok(idx) => Some(&self.files[idx]),
+            Err(0) => None,
+            Err(idx) => Some(&self.files[idx - 1]).filter(|f| pos <= f.end_pos()),
+        }
+    }
+
+    /// Resolves `pos` to a 0-based line, a true character column, and a visual `display_col`, correcting
+    /// the raw byte offset using the `multibyte_chars` and `non_narrow_chars` tables recorded for the line
+    /// at load time (see [`analyze_source_file`](fn.analyze_source_file.html)).
+    ///
+    /// A `pos` that lands exactly on a line's start belongs to that (later) line, not the one before it.
+    pub fn lookup_line_col(&self, pos: BytePos) -> Option<LineCol> {
+        let file = self.lookup_file(pos)?;
+        let line = match file.lines.binary_search(&pos) {
+            Ok(idx) => idx,
+            Err(idx) => idx - 1,
+        };
+        let line_start = file.lines[line];
+
+        // Counts characters (not bytes) in `[lo, hi)`, using `multibyte_chars` to collapse each
+        // multibyte character's extra bytes down to one.
+        let char_count = |lo: BytePos, hi: BytePos| -> usize {
+            let byte_len = (hi.0 - lo.0) as usize;
+            let extra_bytes: usize = file.multibyte_chars
+                .iter()
+                .filter(|&&(p, _)| p >= lo && p < hi)
+                .map(|&(_, len)| len as usize - 1)
+                .sum();
+            byte_len - extra_bytes
+        };
+
+        let col = char_count(line_start, pos);
+
+        // Walk the `non_narrow_chars` recorded for this line, adding up the narrow run before each one
+        // (one column per character) and then that character's own, possibly position-dependent, width.
+        let mut display_col = 0usize;
+        let mut narrow_run_start = line_start;
+        for &(p, kind) in file.non_narrow_chars.iter().filter(|&&(p, _)| p >= line_start && p < pos) {
+            display_col += char_count(narrow_run_start, p);
+            display_col += non_narrow_col_width(kind, display_col);
+            let char_len = file.multibyte_chars
+                .iter()
+                .find(|&&(mp, _)| mp == p)
+                .map_or(1, |&(_, len)| len as u32);
+            narrow_run_start = BytePos(p.0 + char_len);
+        }
+        display_col += char_count(narrow_run_start, pos);
+
+        Some(LineCol { line, col, display_col })
+    }
+
+    /// Returns the exact source text covered by `span`, or `None` if `span` is reversed or does not fall
+    /// within a single loaded file (see [`is_valid_span`](#method.is_valid_span)).
+    pub fn span_to_snippet(&self, span: Span) -> Option<&str> {
+        if !self.is_valid_span(span) {
+            return None;
+        }
+        let file = self.lookup_file(span.lo)?;
+        let lo = (span.lo.0 - file.start_pos.0) as usize;
+        let hi = (span.hi.0 - file.start_pos.0) as usize;
+        file.src.get(lo..hi)
+    }
+
+    /// Renders the source line(s) covered by `span`, underlined with carets and tildes beneath the
+    /// covered region, the way a compiler points at the offending code.
+    ///
+    /// The first line is headed by `span`'s `SpanT`-style location (`name:row,col`); a span covering more
+    /// than one line underlines to the end of each intermediate line and resumes at the start of the
+    /// next, so the underline always tracks where the span actually is on each line.
+    ///
+    /// Returns `None` for a reversed or out-of-file span (see [`is_valid_span`](#method.is_valid_span)),
+    /// rather than rendering a caret-less, meaningless marker line.
+    pub fn span_to_diagnostic_string(&self, span: Span) -> Option<String> {
+        if !self.is_valid_span(span) {
+            return None;
+        }
+        let file = self.lookup_file(span.lo)?;
+        let start = self.lookup_line_col(span.lo)?;
+        let end = self.lookup_line_col(span.hi)?;
+        let empty = span.lo == span.hi;
+
+        let header = SpanT::new(
+            file.name.as_str(),
+            Loc::new(span.lo.to_usize(), start.line, start.col).with_display_col(start.display_col),
+            Loc::new(span.hi.to_usize(), end.line, end.col).with_display_col(end.display_col),
+        );
+        let mut out = format!("{}\n", header);
+
+        for line in start.line..=end.line {
+            let line_lo = file.lines[line];
+            let line_hi = file.lines.get(line + 1).copied().unwrap_or_else(|| file.end_pos());
+            let line_src = &file.src[
+                (line_lo.0 - file.start_pos.0) as usize..(line_hi.0 - file.start_pos.0) as usize
+            ];
+            let line_src = line_src.trim_end_matches(&['\n', '\r'][..]);
+            out.push_str(line_src);
+            out.push('\n');
+
+            let underline_lo = if line == start.line { start.display_col } else { 0 };
+            let underline_hi = if line == end.line {
+                end.display_col
+            } else {
+                let mut col_v = 0;
+                for ch in line_src.chars() {
+                    col_v += visual_width(ch, col_v);
+                }
+                col_v
+            };
+            // A zero-width span still has to point at *something*.
+            let underline_hi = if empty { underline_hi.max(underline_lo + 1) } else { underline_hi };
+
+            let mut marker = String::new();
+            let mut first = true;
+            let mut col_v = 0;
+            for ch in line_src.chars() {
+                if col_v >= underline_hi {
+                    break;
+                }
+                let w = visual_width(ch, col_v);
+                if col_v + w <= underline_lo {
+                    if ch == '\t' {
+                        marker.push('\t');
+                    } else {
+                        marker.extend(std::iter::repeat(' ').take(w));
+                    }
+                } else {
+                    for _ in 0..w {
+                        marker.push(if first { '^' } else { '~' });
+                        first = false;
+                    }
+                }
+                col_v += w;
+            }
+            // The span points past the last character on the line (e.g. at end-of-line); still mark it.
+            while col_v < underline_hi {
+                marker.push(if first { '^' } else { '~' });
+                first = false;
+                col_v += 1;
+            }
+            out.push_str(&marker);
+            out.push('\n');
+        }
+
+        Some(out)
+    }
+
+    /// Returns whether `span` is well-formed: `lo <= hi`, and both ends resolve to the same loaded
+    /// `SourceFile`.
+    ///
+    /// Callers that build spans by hand should check this before handing the span to a diagnostic, since
+    /// a `Span` is just a pair of `BytePos`s and nothing stops one from being reversed or straddling a
+    /// file boundary.
+    pub fn is_valid_span(&self, span: Span) -> bool {
+        if span.lo > span.hi {
+            return false;
+        }
+        match self.lookup_file(span.lo) {
+            Some(file) => span.hi <= file.end_pos(),
+            None => false,
+        }
+    }
+
+    /// Merges two spans into one covering both, or returns `None` if they resolve to different
+    /// `SourceFile`s or do not touch or overlap, mirroring how a `SourceMap` refuses to let a span cross
+    /// a file boundary or fabricate coverage over a gap neither input actually spans.
+    ///
+    /// Unlike [`SpanT::extend_to_cover`](../ast/struct.SpanT.html#method.extend_to_cover), which blindly
+    /// `min`/`max`es two `Loc`s and keeps `self`'s file name, this checks that `a` and `b` actually share
+    /// a file, and are adjacent or overlapping, before covering them.
+    pub fn merge_spans(&self, a: Span, b: Span) -> Option<Span> {
+        if a.lo > a.hi || b.lo > b.hi {
+            return None;
+        }
+        let file_a = self.lookup_file(a.lo)?;
+        let file_b = self.lookup_file(b.lo)?;
+        if !Rc::ptr_eq(file_a, file_b) {
+            return None;
+        }
+        if a.hi > file_a.end_pos() || b.hi > file_a.end_pos() {
+            return None;
+        }
+        if a.hi < b.lo || b.hi < a.lo {
+            return None;
+        }
+        Some(Span::new(a.lo.min(b.lo), a.hi.max(b.hi)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_newline_adds_a_final_empty_line() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "a\nb\n");
+        let lc = map.lookup_line_col(file.end_pos()).unwrap();
+        assert_eq!(lc.line, 2);
+        assert_eq!(lc.col, 0);
+    }
+
+    #[test]
+    fn pos_on_a_line_boundary_belongs_to_the_later_line() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "ab\ncd");
+        let line_start = BytePos(file.start_pos.0 + 3); // just past the '\n', start of line 1
+        let lc = map.lookup_line_col(line_start).unwrap();
+        assert_eq!(lc.line, 1);
+        assert_eq!(lc.col, 0);
+    }
+
+    #[test]
+    fn crlf_counts_as_a_single_line_break() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "ab\r\ncd");
+        let d = BytePos(file.start_pos.0 + 5);
+        let lc = map.lookup_line_col(d).unwrap();
+        assert_eq!(lc.line, 1);
+        assert_eq!(lc.col, 1);
+    }
+
+    #[test]
+    fn tabs_expand_to_the_next_tab_stop() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "\tx");
+        let x = BytePos(file.start_pos.0 + 1);
+        let lc = map.lookup_line_col(x).unwrap();
+        assert_eq!(lc.col, 1);
+        assert_eq!(lc.display_col, TAB_SIZE);
+    }
+
+    #[test]
+    fn wide_chars_count_as_two_display_columns() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "界let");
+        let l = BytePos(file.start_pos.0 + '界'.len_utf8() as u32);
+        let lc = map.lookup_line_col(l).unwrap();
+        assert_eq!(lc.col, 1);
+        assert_eq!(lc.display_col, 2);
+    }
+
+    #[test]
+    fn zero_width_chars_do_not_advance_the_display_column() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "e\u{0301}x"); // 'e' + combining acute accent + 'x'
+        let x = BytePos(file.start_pos.0 + 'e'.len_utf8() as u32 + '\u{0301}'.len_utf8() as u32);
+        let lc = map.lookup_line_col(x).unwrap();
+        assert_eq!(lc.col, 2);
+        assert_eq!(lc.display_col, 1);
+    }
+
+    #[test]
+    fn merge_spans_rejects_spans_from_different_files() {
+        let mut map = SourceMap::new();
+        let a = map.load_file("a.js", "abc");
+        let b = map.load_file("b.js", "def");
+        let span_a = Span::new(a.start_pos, BytePos(a.start_pos.0 + 1));
+        let span_b = Span::new(b.start_pos, BytePos(b.start_pos.0 + 1));
+        assert_eq!(map.merge_spans(span_a, span_b), None);
+    }
+
+    #[test]
+    fn merge_spans_rejects_non_adjacent_non_overlapping_spans() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "0123456789");
+        let a = Span::new(file.start_pos, BytePos(file.start_pos.0 + 2));
+        let b = Span::new(BytePos(file.start_pos.0 + 5), BytePos(file.start_pos.0 + 7));
+        assert_eq!(map.merge_spans(a, b), None);
+    }
+
+    #[test]
+    fn merge_spans_covers_adjacent_or_overlapping_spans() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "0123456789");
+        let a = Span::new(file.start_pos, BytePos(file.start_pos.0 + 3));
+        let b = Span::new(BytePos(file.start_pos.0 + 3), BytePos(file.start_pos.0 + 6));
+        let merged = map.merge_spans(a, b).unwrap();
+        assert_eq!(merged, Span::new(file.start_pos, BytePos(file.start_pos.0 + 6)));
+    }
+
+    #[test]
+    fn is_valid_span_rejects_a_span_past_its_files_end() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "abc");
+        let past_end = Span::new(file.start_pos, BytePos(file.end_pos().0 + 1));
+        assert!(!map.is_valid_span(past_end));
+    }
+
+    #[test]
+    fn is_valid_span_rejects_a_reversed_span() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "abcdef");
+        let reversed = Span::new(BytePos(file.start_pos.0 + 4), BytePos(file.start_pos.0 + 1));
+        assert!(!map.is_valid_span(reversed));
+        assert_eq!(map.span_to_snippet(reversed), None);
+        assert_eq!(map.span_to_diagnostic_string(reversed), None);
+    }
+
+    #[test]
+    fn merge_spans_rejects_a_reversed_input_span() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "0123456789");
+        let reversed = Span::new(BytePos(file.start_pos.0 + 4), BytePos(file.start_pos.0 + 1));
+        let ok = Span::new(file.start_pos, BytePos(file.start_pos.0 + 2));
+        assert_eq!(map.merge_spans(reversed, ok), None);
+        assert_eq!(map.merge_spans(ok, reversed), None);
+    }
+
+    #[test]
+    fn diagnostic_string_for_a_single_line_span() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "let x = 1;\nlet y = 2;\n");
+        let span = Span::new(BytePos(file.start_pos.0 + 4), BytePos(file.start_pos.0 + 5));
+        assert_eq!(map.span_to_snippet(span), Some("x"));
+        assert_eq!(
+            map.span_to_diagnostic_string(span).unwrap(),
+            "a.js:1,5-6\nlet x = 1;\n    ^\n"
+        );
+    }
+
+    #[test]
+    fn diagnostic_string_for_a_multi_line_span() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "ab\ncde\n");
+        let span = Span::new(BytePos(file.start_pos.0 + 1), BytePos(file.start_pos.0 + 4));
+        assert_eq!(map.span_to_snippet(span), Some("b\nc"));
+        assert_eq!(
+            map.span_to_diagnostic_string(span).unwrap(),
+            "a.js:1,2-2,2\nab\n ^\ncde\n^\n"
+        );
+    }
+
+    #[test]
+    fn diagnostic_string_for_a_span_containing_a_tab() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "\tx = 1;\n");
+        let span = Span::new(BytePos(file.start_pos.0 + 1), BytePos(file.start_pos.0 + 2));
+        assert_eq!(map.span_to_snippet(span), Some("x"));
+        assert_eq!(
+            map.span_to_diagnostic_string(span).unwrap(),
+            "a.js:1,5-6\n\tx = 1;\n\t^\n"
+        );
+    }
+
+    #[test]
+    fn diagnostic_string_for_a_span_containing_a_wide_char() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "界x\n");
+        let jie_len = '界'.len_utf8() as u32;
+        let span = Span::new(
+            BytePos(file.start_pos.0 + jie_len),
+            BytePos(file.start_pos.0 + jie_len + 1),
+        );
+        assert_eq!(map.span_to_snippet(span), Some("x"));
+        assert_eq!(
+            map.span_to_diagnostic_string(span).unwrap(),
+            "a.js:1,3-4\n界x\n  ^\n"
+        );
+    }
+
+    /// Builds a `Span<'_>`-style header the way `span_to_diagnostic_string` does, from `SourceMap`-resolved
+    /// `LineCol`s, so `SpanT`'s `Display` impl can be exercised directly against a visual column.
+    fn header<'f>(name: &'f str, lo: LineCol, lo_pos: usize, hi: LineCol, hi_pos: usize) -> SpanT<&'f str> {
+        SpanT::new(
+            name,
+            Loc::new(lo_pos, lo.line, lo.col).with_display_col(lo.display_col),
+            Loc::new(hi_pos, hi.line, hi.col).with_display_col(hi.display_col),
+        )
+    }
+
+    #[test]
+    fn span_display_uses_the_visual_column_for_a_tab() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "\tx");
+        let lo = map.lookup_line_col(file.start_pos).unwrap();
+        let hi = map.lookup_line_col(BytePos(file.start_pos.0 + 1)).unwrap();
+        let span = header(&file.name, lo, 0, hi, 1);
+        assert_eq!(span.to_string(), "a.js:1,1-5");
+    }
+
+    #[test]
+    fn span_display_uses_the_visual_column_for_a_wide_char() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("b.js", "界x");
+        let jie_len = '界'.len_utf8() as u32;
+        let lo = map.lookup_line_col(file.start_pos).unwrap();
+        let hi = map.lookup_line_col(BytePos(file.start_pos.0 + jie_len)).unwrap();
+        let span = header(&file.name, lo, 0, hi, jie_len as usize);
+        assert_eq!(span.to_string(), "b.js:1,1-3");
+    }
+
+    #[test]
+    fn diagnostic_string_for_an_empty_span_at_eof() {
+        let mut map = SourceMap::new();
+        let file = map.load_file("a.js", "abc");
+        let span = Span::new(file.end_pos(), file.end_pos());
+        assert_eq!(map.span_to_snippet(span), Some(""));
+        assert_eq!(
+            map.span_to_diagnostic_string(span).unwrap(),
+            "a.js:1,4\nabc\n   ^\n"
+        );
+    }
+}